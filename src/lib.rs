@@ -8,9 +8,10 @@
 //! Limit calls to an API to 5 per second, or lockout for one minute
 //!
 //! ```
+//! use std::time::Duration;
 //! use throttle2::Throttle;
 //!
-//! let mut counter = Throttle::new(1000, 5, 1000*60);
+//! let mut counter = Throttle::new(Duration::from_millis(1000), 5, Duration::from_secs(60));
 //! if counter.is_throttled() {
 //!     println!("Try again later")
 //! }
@@ -20,9 +21,39 @@
 //! lockout for 5 minutes.
 //!
 //! ```
+//! use std::time::Duration;
 //! use throttle2::ThrottleHash;
 //!
-//! let mut counter = ThrottleHash::new(60*1000, 5, 3*60*1000);
+//! let mut counter = ThrottleHash::new(Duration::from_secs(60), 5, Duration::from_secs(5*60));
+//! let email:String = "john@example.com".to_string();
+//! if counter.is_throttled(&email) {
+//!     println!("Try again later")
+//! }
+//! ```
+//!
+//! Smooth out bursts instead of hard-locking on a window boundary by using
+//! a token-bucket counter. This allows 5 hits per second on average, while
+//! still letting a client burst up to 10 hits before it is throttled.
+//!
+//! ```
+//! use throttle2::Throttle;
+//!
+//! let mut counter = Throttle::new_token_bucket(5, 10);
+//! if counter.is_throttled() {
+//!     println!("Try again later")
+//! }
+//! ```
+//!
+//! Share a throttle across worker threads without serializing every check
+//! behind one global lock. Different keys can be checked concurrently since
+//! [`SyncThrottleHash::is_throttled`] only needs a shared borrow.
+//!
+//! ```
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use throttle2::SyncThrottleHash;
+//!
+//! let counter = Arc::new(SyncThrottleHash::new(Duration::from_secs(60), 5, Duration::from_secs(3*60)));
 //! let email:String = "john@example.com".to_string();
 //! if counter.is_throttled(&email) {
 //!     println!("Try again later")
@@ -32,7 +63,48 @@
 
 use core::hash::Hash;
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A source of the current time, in milliseconds since an arbitrary epoch
+/// (only differences between calls are meaningful). Injectable so a mock
+/// clock can drive the window/lockout tests deterministically instead of
+/// sleeping in real time; defaults to [`SystemClock`].
+pub trait Clock {
+    fn now(&self) -> u128;
+}
+
+/// The default [`Clock`], backed by [`SystemTime::now`].
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    }
+}
+
+/// The result of a throttle check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The activity is allowed to proceed.
+    Allow,
+    /// The activity is throttled; wait `retry_after_ms` before retrying.
+    Throttled { retry_after_ms: u128 },
+}
+
+/// The rate limiting algorithm backing a [`Counter`].
+enum Algorithm {
+    /// Hard fixed-window counter: allow up to `max_hits_in_interval` hits per
+    /// `interval_duration`, then lock out for `lockout_duration`.
+    Window,
+    /// Token bucket: tokens regenerate continuously up to `max_tokens`, and
+    /// each hit consumes `packet_cost` tokens (in nanoseconds of "budget").
+    TokenBucket { packet_cost: u64, max_tokens: u64 },
+}
 
 /// Throttle is an activity counter that can be used to monitor
 /// and limit activity such as incoming connections and sign in
@@ -42,78 +114,220 @@ pub struct Throttle {
     max_hits_in_interval: u64,
     lockout_duration: u128,
     counter: Counter,
+    algorithm: Algorithm,
+    clock: Box<dyn Clock>,
 }
 
 pub struct Counter {
     interval_start: u128,
     current_hit_counter: u64,
     locked_until: u128,
+    last_time: u128,
+    tokens: u64,
+}
+
+/// A point-in-time snapshot of a [`Counter`]'s state, suitable for
+/// persisting to disk (e.g. as JSON, with a serialization crate of the
+/// caller's choice) so that lockouts survive a process restart. All
+/// timestamps are readings of the throttle's [`Clock`] (or that reading in
+/// nanoseconds for `last_time` in token-bucket mode); with the default
+/// [`SystemClock`] these are absolute epoch-millis, so an imported snapshot
+/// remains valid regardless of how long the process was down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterSnapshot {
+    pub interval_start: u128,
+    pub current_hit_counter: u64,
+    pub locked_until: u128,
+    pub last_time: u128,
+    pub tokens: u64,
+}
+
+impl From<&Counter> for CounterSnapshot {
+    fn from(counter: &Counter) -> Self {
+        CounterSnapshot {
+            interval_start: counter.interval_start,
+            current_hit_counter: counter.current_hit_counter,
+            locked_until: counter.locked_until,
+            last_time: counter.last_time,
+            tokens: counter.tokens,
+        }
+    }
+}
+
+impl From<CounterSnapshot> for Counter {
+    fn from(snapshot: CounterSnapshot) -> Self {
+        Counter {
+            interval_start: snapshot.interval_start,
+            current_hit_counter: snapshot.current_hit_counter,
+            locked_until: snapshot.locked_until,
+            last_time: snapshot.last_time,
+            tokens: snapshot.tokens,
+        }
+    }
 }
 
 impl Throttle {
     /// Within `interval` only allow `max_hits` or the locked status is set for `lockout_duration`
-    pub fn new(interval: u128, max_hits: u64, lockout_duration: u128) -> Throttle {
-        //println!("Maximum {} hits in {} millisconds.\n", max_hits, interval);
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
+    pub fn new(interval: Duration, max_hits: u64, lockout_duration: Duration) -> Throttle {
+        Throttle::with_clock(interval, max_hits, lockout_duration, SystemClock)
+    }
+
+    /// Like [`Throttle::new`], but sourcing the current time from `clock`
+    /// instead of [`SystemClock`]. Useful in tests to advance time on
+    /// command rather than sleeping in real time.
+    pub fn with_clock(
+        interval: Duration,
+        max_hits: u64,
+        lockout_duration: Duration,
+        clock: impl Clock + 'static,
+    ) -> Throttle {
+        let clock: Box<dyn Clock> = Box::new(clock);
+        let now = clock.now();
         Throttle {
-            interval_duration: interval,
+            interval_duration: interval.as_millis(),
             max_hits_in_interval: max_hits,
-            lockout_duration: lockout_duration,
+            lockout_duration: lockout_duration.as_millis(),
             counter: Counter {
                 interval_start: now,
                 current_hit_counter: 0,
                 locked_until: 0,
+                last_time: 0,
+                tokens: 0,
             },
+            algorithm: Algorithm::Window,
+            clock,
+        }
+    }
+
+    /// Allow a steady `rate_per_sec`, while letting a client absorb short
+    /// bursts of up to `burst` hits, using a token-bucket algorithm instead
+    /// of the hard fixed window used by [`Throttle::new`]. This smooths out
+    /// the bursts and hard-locks that a fixed window allows right at its
+    /// boundaries.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate_per_sec` is `0`.
+    pub fn new_token_bucket(rate_per_sec: u64, burst: u64) -> Throttle {
+        Throttle::new_token_bucket_with_clock(rate_per_sec, burst, SystemClock)
+    }
+
+    /// Like [`Throttle::new_token_bucket`], but sourcing the current time
+    /// from `clock` instead of [`SystemClock`]. Useful in tests to advance
+    /// time on command rather than sleeping in real time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate_per_sec` is `0`.
+    pub fn new_token_bucket_with_clock(
+        rate_per_sec: u64,
+        burst: u64,
+        clock: impl Clock + 'static,
+    ) -> Throttle {
+        assert!(rate_per_sec > 0, "rate_per_sec must be greater than zero");
+        let clock: Box<dyn Clock> = Box::new(clock);
+        let now_millis = clock.now();
+        let packet_cost = 1_000_000_000 / rate_per_sec;
+        let max_tokens = packet_cost * burst;
+        Throttle {
+            interval_duration: 0,
+            max_hits_in_interval: 0,
+            lockout_duration: 0,
+            counter: Counter {
+                interval_start: now_millis,
+                current_hit_counter: 0,
+                locked_until: 0,
+                last_time: token_bucket_nanos(now_millis),
+                tokens: max_tokens,
+            },
+            algorithm: Algorithm::TokenBucket {
+                packet_cost,
+                max_tokens,
+            },
+            clock,
         }
     }
 
     /// When a monitored activity occurs, `is_throttled()` counts that event and
     /// returns `true` if the activity count has exceeded the limit.
     pub fn is_throttled(&mut self) -> bool {
-        self.counter.current_hit_counter += 1;
-        let mut now: u128 = 0;
-        if self.counter.locked_until != 0 {
-            now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis();
-            if self.counter.locked_until > now {
-                return true;
-            }
-            self.counter.locked_until = 0;
-        }
-        if self.counter.current_hit_counter <= self.max_hits_in_interval {
-            return false;
-        }
-        if now == 0 {
-            now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis();
-        }
-        if self.counter.locked_until > 0 {
-            if self.counter.locked_until > now {
-                return true;
-            }
-            //println!("reset all");
-            self.counter.interval_start = now;
-            self.counter.locked_until = 0;
-            self.counter.current_hit_counter = 1;
-            return false;
-        }
-        if now - self.counter.interval_start <= self.interval_duration {
-            self.counter.interval_start = now;
-            self.counter.current_hit_counter = 1;
-            self.counter.locked_until = now + self.lockout_duration;
-            return true;
+        matches!(self.check(), Decision::Throttled { .. })
+    }
+
+    /// Like [`Throttle::is_throttled`], but on throttling also returns how
+    /// long the caller should wait before retrying, e.g. for a
+    /// `Retry-After` header or to `sleep` in an async loop.
+    pub fn check(&mut self) -> Decision {
+        match self.algorithm {
+            Algorithm::Window => check_window(
+                &mut self.counter,
+                self.clock.as_ref(),
+                self.interval_duration,
+                self.max_hits_in_interval,
+                self.lockout_duration,
+            ),
+            Algorithm::TokenBucket {
+                packet_cost,
+                max_tokens,
+            } => check_token_bucket(&mut self.counter, self.clock.as_ref(), packet_cost, max_tokens),
         }
-        self.counter.interval_start = now;
-        self.counter.current_hit_counter = 1;
+    }
+}
+
+/// Convert a [`Clock::now`] millisecond reading into the synthetic
+/// nanosecond timeline [`check_token_bucket`] does its bookkeeping in. This
+/// caps the token bucket's resolution at a millisecond (irrelevant for
+/// [`SystemClock`], and exactly what lets a millisecond-granularity mock
+/// clock drive it deterministically in tests).
+fn token_bucket_nanos(now_millis: u128) -> u128 {
+    now_millis * 1_000_000
+}
+
+/// Shared token-bucket logic for both [`Throttle`] and [`ThrottleHash`]:
+/// tokens regenerate continuously with the passage of time (in nanoseconds)
+/// up to `max_tokens`, and each call consumes `packet_cost` tokens if
+/// available.
+fn check_token_bucket(
+    counter: &mut Counter,
+    clock: &dyn Clock,
+    packet_cost: u64,
+    max_tokens: u64,
+) -> Decision {
+    let now = token_bucket_nanos(clock.now());
+    let elapsed = now.saturating_sub(counter.last_time) as u64;
+    counter.tokens = std::cmp::min(max_tokens, counter.tokens.saturating_add(elapsed));
+    counter.last_time = now;
+    if counter.tokens >= packet_cost {
+        counter.tokens -= packet_cost;
+        return Decision::Allow;
+    }
+    let shortfall_nanos = (packet_cost - counter.tokens) as u128;
+    Decision::Throttled {
+        retry_after_ms: shortfall_nanos.div_ceil(1_000_000),
+    }
+}
+
+/// Whether `counter` is idle enough for [`ThrottleHash::gc`] /
+/// [`SyncThrottleHash::gc`] to reap it: no lockout recorded (`locked_until`
+/// is `0`; an expired lockout stays recorded until the key's next hit), and
+/// a full window has passed. For [`Algorithm::Window`] that means
+/// `interval_start` is more than `interval_duration` old (hits within a
+/// window do not move `interval_start`); a token bucket has no interval, so
+/// it must have gone untouched for as long as it takes an empty bucket to
+/// regenerate to `max_tokens` (after that much idle time, it's back to
+/// exactly the state a freshly-inserted entry would have).
+fn is_idle(counter: &Counter, algorithm: &Algorithm, now_millis: u128, interval_duration: u128) -> bool {
+    if counter.locked_until != 0 {
         return false;
     }
+    match algorithm {
+        Algorithm::Window => counter.interval_start < now_millis.saturating_sub(interval_duration),
+        Algorithm::TokenBucket { max_tokens, .. } => {
+            let refill_duration_millis = (*max_tokens as u128).div_ceil(1_000_000);
+            let last_touched_millis = counter.last_time / 1_000_000;
+            last_touched_millis < now_millis.saturating_sub(refill_duration_millis)
+        }
+    }
 }
 
 pub struct ThrottleHash<H: Eq + Hash + Clone> {
@@ -121,167 +335,581 @@ pub struct ThrottleHash<H: Eq + Hash + Clone> {
     max_hits_in_interval: u64,
     lockout_duration: u128,
     counters: HashMap<H, Counter>,
+    algorithm: Algorithm,
+    clock: Box<dyn Clock + Send>,
 }
 
 impl<H: Eq + Hash + Clone> ThrottleHash<H> {
     /// Within `interval` only allow `max_hits` or the locked status is set for `lockout_duration`
-    pub fn new(interval: u128, max_hits: u64, lockout_duration: u128) -> Self {
-        //println!("Maximum {} hits in {} millisconds.\n", max_hits, interval);
+    pub fn new(interval: Duration, max_hits: u64, lockout_duration: Duration) -> Self {
+        ThrottleHash::with_clock(interval, max_hits, lockout_duration, SystemClock)
+    }
+
+    /// Like [`ThrottleHash::new`], but sourcing the current time from
+    /// `clock` instead of [`SystemClock`]. Useful in tests to advance time
+    /// on command rather than sleeping in real time.
+    pub fn with_clock(
+        interval: Duration,
+        max_hits: u64,
+        lockout_duration: Duration,
+        clock: impl Clock + Send + 'static,
+    ) -> Self {
         ThrottleHash {
-            interval_duration: interval,
+            interval_duration: interval.as_millis(),
             max_hits_in_interval: max_hits,
-            lockout_duration: lockout_duration,
+            lockout_duration: lockout_duration.as_millis(),
+            counters: HashMap::<H, Counter>::new(),
+            algorithm: Algorithm::Window,
+            clock: Box::new(clock),
+        }
+    }
+
+    /// Allow a steady `rate_per_sec` per key, while letting each key absorb
+    /// short bursts of up to `burst` hits, using a token-bucket algorithm
+    /// instead of the hard fixed window used by [`ThrottleHash::new`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate_per_sec` is `0`.
+    pub fn new_token_bucket(rate_per_sec: u64, burst: u64) -> Self {
+        ThrottleHash::new_token_bucket_with_clock(rate_per_sec, burst, SystemClock)
+    }
+
+    /// Like [`ThrottleHash::new_token_bucket`], but sourcing the current
+    /// time from `clock` instead of [`SystemClock`]. Useful in tests to
+    /// advance time on command rather than sleeping in real time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate_per_sec` is `0`.
+    pub fn new_token_bucket_with_clock(
+        rate_per_sec: u64,
+        burst: u64,
+        clock: impl Clock + Send + 'static,
+    ) -> Self {
+        assert!(rate_per_sec > 0, "rate_per_sec must be greater than zero");
+        let packet_cost = 1_000_000_000 / rate_per_sec;
+        let max_tokens = packet_cost * burst;
+        ThrottleHash {
+            interval_duration: 0,
+            max_hits_in_interval: 0,
+            lockout_duration: 0,
             counters: HashMap::<H, Counter>::new(),
+            algorithm: Algorithm::TokenBucket {
+                packet_cost,
+                max_tokens,
+            },
+            clock: Box::new(clock),
         }
     }
 
     /// When a monitored activity occurs, `is_throttled()` counts that event and
     /// returns `true` if the activity count has exceeded the limit.
     pub fn is_throttled(&mut self, key: &H) -> bool {
+        matches!(self.check(key), Decision::Throttled { .. })
+    }
+
+    /// Like [`ThrottleHash::is_throttled`], but on throttling also returns
+    /// how long the caller should wait before retrying, e.g. for a
+    /// `Retry-After` header or to `sleep` in an async loop.
+    pub fn check(&mut self, key: &H) -> Decision {
         let counter = match self.counters.get_mut(key) {
             Some(c) => c,
             None => {
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis();
+                let now_millis = self.clock.now();
+                let (last_time, tokens) = match self.algorithm {
+                    Algorithm::Window => (0, 0),
+                    Algorithm::TokenBucket { max_tokens, .. } => {
+                        (token_bucket_nanos(now_millis), max_tokens)
+                    }
+                };
                 let c = Counter {
-                    interval_start: now,
+                    interval_start: now_millis,
                     locked_until: 0,
                     current_hit_counter: 0,
+                    last_time,
+                    tokens,
                 };
                 self.counters.insert(key.clone(), c);
                 self.counters.get_mut(key).unwrap()
             }
         };
 
-        counter.current_hit_counter += 1;
-        let mut now: u128 = 0;
-        if counter.locked_until != 0 {
-            now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis();
-            if counter.locked_until > now {
-                return true;
+        match self.algorithm {
+            Algorithm::Window => check_window(
+                counter,
+                self.clock.as_ref(),
+                self.interval_duration,
+                self.max_hits_in_interval,
+                self.lockout_duration,
+            ),
+            Algorithm::TokenBucket {
+                packet_cost,
+                max_tokens,
+            } => check_token_bucket(counter, self.clock.as_ref(), packet_cost, max_tokens),
+        }
+    }
+
+    /// Drop any entry that is idle: no lockout recorded, and its window
+    /// started more than a full interval ago (for a token-bucket entry,
+    /// untouched long enough for its bucket to have fully refilled). Call
+    /// this periodically, or use
+    /// [`ThrottleHash::spawn_gc`], to keep the table from growing without
+    /// bound when it is keyed on e.g. client IPs or emails.
+    pub fn gc(&mut self) {
+        let now_millis = self.clock.now();
+        let algorithm = &self.algorithm;
+        let interval_duration = self.interval_duration;
+        self.counters
+            .retain(|_, counter| !is_idle(counter, algorithm, now_millis, interval_duration));
+    }
+
+    /// Capture every entry's state as a [`CounterSnapshot`], so it can be
+    /// persisted (e.g. to disk) and later restored with
+    /// [`ThrottleHash::import`] after a process restart, without losing
+    /// in-progress lockouts.
+    pub fn export(&self) -> Vec<(H, CounterSnapshot)> {
+        self.counters
+            .iter()
+            .map(|(key, counter)| (key.clone(), CounterSnapshot::from(counter)))
+            .collect()
+    }
+
+    /// Restore entries previously captured by [`ThrottleHash::export`].
+    /// With the default [`SystemClock`], snapshots store absolute epoch
+    /// timestamps, so an imported lockout remains valid regardless of how
+    /// long the process was down.
+    pub fn import(&mut self, snapshot: Vec<(H, CounterSnapshot)>) {
+        for (key, counter) in snapshot {
+            self.counters.insert(key, counter.into());
+        }
+    }
+}
+
+impl<H: Eq + Hash + Clone + Send + 'static> ThrottleHash<H> {
+    /// Spawn a background thread that calls [`ThrottleHash::gc`] every
+    /// `interval`. The thread is stopped and joined when the returned
+    /// [`GcHandle`] is dropped.
+    pub fn spawn_gc(throttle: Arc<Mutex<Self>>, interval: Duration) -> GcHandle {
+        let shutdown = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_shutdown = shutdown.clone();
+        let thread = thread::spawn(move || {
+            while wait_for_tick(&thread_shutdown, interval) {
+                throttle.lock().unwrap().gc();
+            }
+        });
+        GcHandle {
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+
+    /// Spawn a background thread that calls `save` with an
+    /// [`ThrottleHash::export`] snapshot every `interval`, so a long-lived
+    /// login-throttle or IP-throttle can persist its in-progress lockouts
+    /// (e.g. by JSON-encoding the snapshot and writing it to disk) and
+    /// restore them on the next process start via [`ThrottleHash::import`].
+    /// The thread is stopped and joined when the returned [`GcHandle`] is
+    /// dropped.
+    pub fn spawn_persist(
+        throttle: Arc<Mutex<Self>>,
+        interval: Duration,
+        save: impl Fn(Vec<(H, CounterSnapshot)>) + Send + 'static,
+    ) -> GcHandle {
+        let shutdown = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_shutdown = shutdown.clone();
+        let thread = thread::spawn(move || {
+            while wait_for_tick(&thread_shutdown, interval) {
+                let snapshot = throttle.lock().unwrap().export();
+                save(snapshot);
+            }
+        });
+        GcHandle {
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Block until `shutdown` is signalled or `interval` elapses, returning
+/// `true` if it's time to run the periodic action (a normal tick) or
+/// `false` if the wait ended because shutdown was signalled. Waiting on the
+/// [`Condvar`] (instead of `thread::sleep` plus polling a flag) means
+/// [`GcHandle::drop`] wakes the background thread immediately rather than
+/// blocking for up to a full `interval`.
+fn wait_for_tick(shutdown: &Arc<(Mutex<bool>, Condvar)>, interval: Duration) -> bool {
+    let (lock, cvar) = &**shutdown;
+    let guard = lock.lock().unwrap();
+    if *guard {
+        return false;
+    }
+    // `wait_timeout_while` re-waits on spurious wakeups, so the wait only
+    // ends early when shutdown is signalled.
+    let (guard, _) = cvar
+        .wait_timeout_while(guard, interval, |shutdown| !*shutdown)
+        .unwrap();
+    !*guard
+}
+
+/// A handle to the background thread spawned by [`ThrottleHash::spawn_gc`].
+/// Dropping it signals the thread to stop and joins it.
+pub struct GcHandle {
+    shutdown: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for GcHandle {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.shutdown;
+        *lock.lock().unwrap() = true;
+        cvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A thread-safe variant of [`ThrottleHash`] for concurrent servers. Checks
+/// against different keys can proceed concurrently: the hot path only takes
+/// a per-key lock, and the table itself is write-locked only when inserting
+/// a new key.
+pub struct SyncThrottleHash<H: Eq + Hash + Clone> {
+    interval_duration: u128,
+    max_hits_in_interval: u64,
+    lockout_duration: u128,
+    algorithm: Algorithm,
+    counters: RwLock<HashMap<H, Mutex<Counter>>>,
+    clock: Box<dyn Clock + Send + Sync>,
+}
+
+impl<H: Eq + Hash + Clone> SyncThrottleHash<H> {
+    /// Within `interval` only allow `max_hits` or the locked status is set for `lockout_duration`
+    pub fn new(interval: Duration, max_hits: u64, lockout_duration: Duration) -> Self {
+        SyncThrottleHash::with_clock(interval, max_hits, lockout_duration, SystemClock)
+    }
+
+    /// Like [`SyncThrottleHash::new`], but sourcing the current time from
+    /// `clock` instead of [`SystemClock`]. Useful in tests to advance time
+    /// on command rather than sleeping in real time.
+    pub fn with_clock(
+        interval: Duration,
+        max_hits: u64,
+        lockout_duration: Duration,
+        clock: impl Clock + Send + Sync + 'static,
+    ) -> Self {
+        SyncThrottleHash {
+            interval_duration: interval.as_millis(),
+            max_hits_in_interval: max_hits,
+            lockout_duration: lockout_duration.as_millis(),
+            algorithm: Algorithm::Window,
+            counters: RwLock::new(HashMap::new()),
+            clock: Box::new(clock),
+        }
+    }
+
+    /// Allow a steady `rate_per_sec` per key, while letting each key absorb
+    /// short bursts of up to `burst` hits, using a token-bucket algorithm
+    /// instead of the hard fixed window used by [`SyncThrottleHash::new`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate_per_sec` is `0`.
+    pub fn new_token_bucket(rate_per_sec: u64, burst: u64) -> Self {
+        SyncThrottleHash::new_token_bucket_with_clock(rate_per_sec, burst, SystemClock)
+    }
+
+    /// Like [`SyncThrottleHash::new_token_bucket`], but sourcing the current
+    /// time from `clock` instead of [`SystemClock`]. Useful in tests to
+    /// advance time on command rather than sleeping in real time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate_per_sec` is `0`.
+    pub fn new_token_bucket_with_clock(
+        rate_per_sec: u64,
+        burst: u64,
+        clock: impl Clock + Send + Sync + 'static,
+    ) -> Self {
+        assert!(rate_per_sec > 0, "rate_per_sec must be greater than zero");
+        let packet_cost = 1_000_000_000 / rate_per_sec;
+        let max_tokens = packet_cost * burst;
+        SyncThrottleHash {
+            interval_duration: 0,
+            max_hits_in_interval: 0,
+            lockout_duration: 0,
+            algorithm: Algorithm::TokenBucket {
+                packet_cost,
+                max_tokens,
+            },
+            counters: RwLock::new(HashMap::new()),
+            clock: Box::new(clock),
+        }
+    }
+
+    fn new_counter(&self) -> Counter {
+        let now_millis = self.clock.now();
+        let (last_time, tokens) = match self.algorithm {
+            Algorithm::Window => (0, 0),
+            Algorithm::TokenBucket { max_tokens, .. } => {
+                (token_bucket_nanos(now_millis), max_tokens)
             }
-            counter.locked_until = 0;
+        };
+        Counter {
+            interval_start: now_millis,
+            locked_until: 0,
+            current_hit_counter: 0,
+            last_time,
+            tokens,
         }
-        if counter.current_hit_counter <= self.max_hits_in_interval {
-            return false;
+    }
+
+    /// When a monitored activity occurs, `is_throttled()` counts that event and
+    /// returns `true` if the activity count has exceeded the limit.
+    pub fn is_throttled(&self, key: &H) -> bool {
+        matches!(self.check(key), Decision::Throttled { .. })
+    }
+
+    /// Like [`SyncThrottleHash::is_throttled`], but on throttling also
+    /// returns how long the caller should wait before retrying.
+    pub fn check(&self, key: &H) -> Decision {
+        if let Some(counter) = self.counters.read().unwrap().get(key) {
+            let mut counter = counter.lock().unwrap();
+            return self.check_counter(&mut counter);
         }
-        if now == 0 {
-            now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis();
+        let mut counters = self.counters.write().unwrap();
+        let counter = counters
+            .entry(key.clone())
+            .or_insert_with(|| Mutex::new(self.new_counter()));
+        // The write lock already gives exclusive access to `counter`, so
+        // `get_mut` avoids taking an unnecessary lock on it (unlike the
+        // shared-borrow branch above, which needs the real lock).
+        self.check_counter(counter.get_mut().unwrap())
+    }
+
+    fn check_counter(&self, counter: &mut Counter) -> Decision {
+        match self.algorithm {
+            Algorithm::Window => check_window(
+                counter,
+                self.clock.as_ref(),
+                self.interval_duration,
+                self.max_hits_in_interval,
+                self.lockout_duration,
+            ),
+            Algorithm::TokenBucket {
+                packet_cost,
+                max_tokens,
+            } => check_token_bucket(counter, self.clock.as_ref(), packet_cost, max_tokens),
         }
-        if counter.locked_until > 0 {
-            if counter.locked_until > now {
-                return true;
+    }
+
+    /// Same eviction policy as [`ThrottleHash::gc`].
+    pub fn gc(&self) {
+        let now_millis = self.clock.now();
+        let algorithm = &self.algorithm;
+        let interval_duration = self.interval_duration;
+        self.counters.write().unwrap().retain(|_, counter| {
+            let counter = counter.get_mut().unwrap();
+            !is_idle(counter, algorithm, now_millis, interval_duration)
+        });
+    }
+}
+
+impl<H: Eq + Hash + Clone + Send + Sync + 'static> SyncThrottleHash<H> {
+    /// Spawn a background thread that calls [`SyncThrottleHash::gc`] every
+    /// `interval`. The thread is stopped and joined when the returned
+    /// [`GcHandle`] is dropped.
+    pub fn spawn_gc(throttle: Arc<Self>, interval: Duration) -> GcHandle {
+        let shutdown = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_shutdown = shutdown.clone();
+        let thread = thread::spawn(move || {
+            while wait_for_tick(&thread_shutdown, interval) {
+                throttle.gc();
             }
-            //println!("reset all");
-            counter.interval_start = now;
-            counter.locked_until = 0;
-            counter.current_hit_counter = 1;
-            return false;
+        });
+        GcHandle {
+            shutdown,
+            thread: Some(thread),
         }
-        if now - counter.interval_start <= self.interval_duration {
-            counter.interval_start = now;
-            counter.current_hit_counter = 1;
-            counter.locked_until = now + self.lockout_duration;
-            return true;
+    }
+}
+
+fn check_window(
+    counter: &mut Counter,
+    clock: &dyn Clock,
+    interval_duration: u128,
+    max_hits_in_interval: u64,
+    lockout_duration: u128,
+) -> Decision {
+    counter.current_hit_counter += 1;
+    let mut now: u128 = 0;
+    if counter.locked_until != 0 {
+        now = clock.now();
+        if counter.locked_until > now {
+            return Decision::Throttled {
+                retry_after_ms: counter.locked_until - now,
+            };
         }
+        counter.locked_until = 0;
+    }
+    if counter.current_hit_counter <= max_hits_in_interval {
+        return Decision::Allow;
+    }
+    if now == 0 {
+        now = clock.now();
+    }
+    if counter.locked_until > 0 {
+        if counter.locked_until > now {
+            return Decision::Throttled {
+                retry_after_ms: counter.locked_until - now,
+            };
+        }
+        //println!("reset all");
         counter.interval_start = now;
+        counter.locked_until = 0;
         counter.current_hit_counter = 1;
-        return false;
+        return Decision::Allow;
     }
+    if now - counter.interval_start <= interval_duration {
+        counter.interval_start = now;
+        counter.current_hit_counter = 1;
+        counter.locked_until = now + lockout_duration;
+        return Decision::Throttled {
+            retry_after_ms: lockout_duration,
+        };
+    }
+    counter.interval_start = now;
+    counter.current_hit_counter = 1;
+    return Decision::Allow;
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::Clock;
+    use crate::Decision;
+    use crate::SyncThrottleHash;
     use crate::Throttle;
     use crate::ThrottleHash;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
+
+    /// A [`Clock`] that only advances when told to, so tests can exercise
+    /// window/lockout timing deterministically and instantly.
+    #[derive(Clone)]
+    struct MockClock(Arc<AtomicU64>);
+
+    impl MockClock {
+        fn new() -> Self {
+            MockClock(Arc::new(AtomicU64::new(0)))
+        }
+
+        fn advance(&self, millis: u64) {
+            self.0.fetch_add(millis, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> u128 {
+            self.0.load(Ordering::SeqCst) as u128
+        }
+    }
 
     #[test]
     fn test_throttle() {
-        let mut t = Throttle::new(500, 3, 1000);
+        let clock = MockClock::new();
+        let mut t = Throttle::with_clock(
+            Duration::from_millis(500),
+            3,
+            Duration::from_millis(1000),
+            clock.clone(),
+        );
 
         // Slow and study shouldnt lock
         assert!(!t.is_throttled());
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled());
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled());
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled());
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled());
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled());
         assert!(!t.is_throttled());
         assert!(!t.is_throttled());
         assert!(!t.is_throttled());
 
         assert!(t.is_throttled()); // Trigger and stay triggered for the lockout time
-        thread::sleep(Duration::from_millis(300));
+        clock.advance(300);
         assert!(t.is_throttled());
-        thread::sleep(Duration::from_millis(300));
+        clock.advance(300);
         assert!(t.is_throttled());
-        thread::sleep(Duration::from_millis(500));
+        clock.advance(500);
         assert!(!t.is_throttled());
 
         // Check the throttle still works after the last clear
         assert!(!t.is_throttled());
         assert!(!t.is_throttled());
         assert!(t.is_throttled());
-        thread::sleep(Duration::from_millis(1100));
+        clock.advance(1100);
         assert!(!t.is_throttled());
     }
 
     #[test]
     fn test_throttle_key() {
-        let mut t = ThrottleHash::<String>::new(500, 3, 1000);
+        let clock = MockClock::new();
+        let mut t = ThrottleHash::<String>::with_clock(
+            Duration::from_millis(500),
+            3,
+            Duration::from_millis(1000),
+            clock.clone(),
+        );
 
         let email1 = "bob1@example.com".to_string();
 
         // Slow and study shouldnt lock
         assert!(!t.is_throttled(&email1));
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled(&email1));
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled(&email1));
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled(&email1));
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled(&email1));
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email1));
 
         assert!(t.is_throttled(&email1));
-        thread::sleep(Duration::from_millis(300));
+        clock.advance(300);
         assert!(t.is_throttled(&email1));
-        thread::sleep(Duration::from_millis(300));
+        clock.advance(300);
         assert!(t.is_throttled(&email1));
-        thread::sleep(Duration::from_millis(500));
+        clock.advance(500);
         assert!(!t.is_throttled(&email1));
 
         // Check the throttle still works after the last clear
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email1));
         assert!(t.is_throttled(&email1));
-        thread::sleep(Duration::from_millis(1100));
+        clock.advance(1100);
         assert!(!t.is_throttled(&email1));
     }
 
     #[test]
     fn test_throttle_key_overlap() {
-        let mut t = ThrottleHash::<String>::new(500, 3, 1000);
+        let clock = MockClock::new();
+        let mut t = ThrottleHash::<String>::with_clock(
+            Duration::from_millis(500),
+            3,
+            Duration::from_millis(1000),
+            clock.clone(),
+        );
 
         let email1 = "bob1@example.com".to_string();
         let email2 = "bob2@example.com".to_string();
@@ -289,19 +917,19 @@ mod tests {
         // Slow and study shouldnt lock
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email2));
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email2));
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email2));
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email2));
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email2));
-        thread::sleep(Duration::from_millis(600));
+        clock.advance(600);
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email2));
         assert!(!t.is_throttled(&email1));
@@ -313,13 +941,13 @@ mod tests {
 
         assert!(t.is_throttled(&email1));
         assert!(t.is_throttled(&email2));
-        thread::sleep(Duration::from_millis(300));
+        clock.advance(300);
         assert!(t.is_throttled(&email1));
         assert!(t.is_throttled(&email2));
-        thread::sleep(Duration::from_millis(300));
+        clock.advance(300);
         assert!(t.is_throttled(&email1));
         assert!(t.is_throttled(&email2));
-        thread::sleep(Duration::from_millis(500));
+        clock.advance(500);
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email2));
 
@@ -330,8 +958,190 @@ mod tests {
         assert!(!t.is_throttled(&email2));
         assert!(t.is_throttled(&email1));
         assert!(t.is_throttled(&email2));
-        thread::sleep(Duration::from_millis(1100));
+        clock.advance(1100);
         assert!(!t.is_throttled(&email1));
         assert!(!t.is_throttled(&email2));
     }
+
+    #[test]
+    fn test_throttle_token_bucket_allows_burst_then_smooths() {
+        let clock = MockClock::new();
+        let mut t = Throttle::new_token_bucket_with_clock(10, 5, clock.clone());
+
+        // The bucket starts full, so a burst up to `burst` hits should pass.
+        for _ in 0..5 {
+            assert!(!t.is_throttled());
+        }
+        // The bucket is now empty; the very next hit is throttled.
+        assert!(t.is_throttled());
+
+        // After advancing long enough to regenerate one token, one more hit
+        // should be allowed.
+        clock.advance(110);
+        assert!(!t.is_throttled());
+    }
+
+    #[test]
+    fn test_throttle_hash_token_bucket_per_key() {
+        let mut t = ThrottleHash::<String>::new_token_bucket(10, 3);
+
+        let email1 = "bob1@example.com".to_string();
+        let email2 = "bob2@example.com".to_string();
+
+        for _ in 0..3 {
+            assert!(!t.is_throttled(&email1));
+        }
+        assert!(t.is_throttled(&email1));
+
+        // A different key has its own bucket and is unaffected.
+        assert!(!t.is_throttled(&email2));
+    }
+
+    #[test]
+    fn test_throttle_hash_gc_evicts_idle_keys_but_not_locked_ones() {
+        let clock = MockClock::new();
+        let mut t = ThrottleHash::<String>::with_clock(
+            Duration::from_millis(100),
+            1,
+            Duration::from_millis(1000),
+            clock.clone(),
+        );
+
+        let idle = "idle@example.com".to_string();
+        let locked = "locked@example.com".to_string();
+
+        assert!(!t.is_throttled(&idle));
+        assert!(!t.is_throttled(&locked));
+        assert!(t.is_throttled(&locked)); // triggers the lockout
+
+        clock.advance(150);
+        t.gc();
+
+        // The idle key was not locked out and hasn't been touched since
+        // before the interval, so it should have been reaped.
+        assert_eq!(t.counters.len(), 1);
+        assert!(t.counters.contains_key(&locked));
+    }
+
+    #[test]
+    fn test_throttle_hash_gc_preserves_active_token_bucket_entries() {
+        let clock = MockClock::new();
+        let mut t = ThrottleHash::<String>::new_token_bucket_with_clock(10, 5, clock.clone());
+
+        let key = "bob1@example.com".to_string();
+        assert!(!t.is_throttled(&key));
+        assert!(!t.is_throttled(&key));
+
+        // Only a few ms have passed since the last hit, far less than the
+        // ~500ms this bucket takes to fully refill, so gc() must not treat
+        // it as idle even though `Algorithm::TokenBucket` has no interval.
+        clock.advance(5);
+        t.gc();
+        assert_eq!(t.counters.len(), 1);
+
+        // Once the bucket has had long enough to fully refill without being
+        // touched, it's indistinguishable from a key that was never hit, so
+        // gc() is free to reap it.
+        clock.advance(500);
+        t.gc();
+        assert_eq!(t.counters.len(), 0);
+    }
+
+    #[test]
+    fn test_check_reports_retry_after() {
+        let clock = MockClock::new();
+        let mut t = Throttle::with_clock(
+            Duration::from_millis(500),
+            3,
+            Duration::from_millis(1000),
+            clock,
+        );
+
+        assert_eq!(t.check(), Decision::Allow);
+        assert_eq!(t.check(), Decision::Allow);
+        assert_eq!(t.check(), Decision::Allow);
+
+        match t.check() {
+            Decision::Throttled { retry_after_ms } => {
+                assert!(retry_after_ms > 0 && retry_after_ms <= 1000);
+            }
+            Decision::Allow => panic!("expected to be throttled"),
+        }
+
+        // is_throttled() agrees with check() about whether we're throttled.
+        assert!(t.is_throttled());
+    }
+
+    #[test]
+    fn test_sync_throttle_hash_concurrent_keys() {
+        let clock = MockClock::new();
+        let t = Arc::new(SyncThrottleHash::<String>::with_clock(
+            Duration::from_millis(500),
+            3,
+            Duration::from_millis(1000),
+            clock,
+        ));
+
+        let mut handles = Vec::new();
+        for n in 0..4 {
+            let t = t.clone();
+            handles.push(thread::spawn(move || {
+                let key = format!("client-{}", n);
+                for _ in 0..3 {
+                    assert!(!t.is_throttled(&key));
+                }
+                assert!(t.is_throttled(&key));
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_export_import_preserves_lockout() {
+        let clock = MockClock::new();
+        let mut t = ThrottleHash::<String>::with_clock(
+            Duration::from_millis(500),
+            3,
+            Duration::from_millis(1000),
+            clock.clone(),
+        );
+
+        let email1 = "bob1@example.com".to_string();
+        for _ in 0..3 {
+            assert!(!t.is_throttled(&email1));
+        }
+        assert!(t.is_throttled(&email1)); // triggers the lockout
+
+        let snapshot = t.export();
+
+        // Restoring into a fresh throttle (simulating a process restart,
+        // with the same wall clock continuing to tick) should keep the key
+        // locked out, since snapshots store absolute timestamps.
+        let mut restarted = ThrottleHash::<String>::with_clock(
+            Duration::from_millis(500),
+            3,
+            Duration::from_millis(1000),
+            clock,
+        );
+        restarted.import(snapshot);
+        assert!(restarted.is_throttled(&email1));
+    }
+
+    #[test]
+    fn test_spawn_gc_drop_does_not_block_for_full_interval() {
+        let t = Arc::new(Mutex::new(ThrottleHash::<String>::new(
+            Duration::from_secs(60),
+            3,
+            Duration::from_secs(60),
+        )));
+        let handle = ThrottleHash::spawn_gc(t, Duration::from_secs(5));
+
+        // Dropping the handle must wake the background thread via the
+        // condvar rather than leaving it asleep for the full interval.
+        let start = Instant::now();
+        drop(handle);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
 }